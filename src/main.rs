@@ -1,10 +1,36 @@
+mod server;
+
 use anyhow::Result;
+use fs2::FileExt;
 use regex::Regex;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use std::fmt::Formatter;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use thiserror::Error;
 
+/// The map type backing the store. Behind the `preserve_order` feature this
+/// is an insertion-ordered map so `Keys`, iteration, and on-disk layout stay
+/// stable across runs; otherwise it's a plain `HashMap`.
+#[cfg(feature = "preserve_order")]
+type Map<K, V> = indexmap::IndexMap<K, V>;
+#[cfg(not(feature = "preserve_order"))]
+type Map<K, V> = std::collections::HashMap<K, V>;
+
+/// Removes `key` from a [`Map`]. Under `preserve_order`, `IndexMap::remove`
+/// is a deprecated alias for `swap_remove`, which would reorder the
+/// remaining entries; `shift_remove` is the one that preserves insertion
+/// order, which is the entire point of the feature.
+#[cfg(feature = "preserve_order")]
+fn map_remove(map: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    map.shift_remove(key)
+}
+#[cfg(not(feature = "preserve_order"))]
+fn map_remove(map: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    map.remove(key)
+}
+
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     //println!("{:?}", opt.command);
@@ -51,6 +77,29 @@ fn main() -> Result<()> {
             let keys = store.get_keys(pattern)?;
             format!("Keys : {}", keys.join(", "))
         }
+        Command::Schema { key, schema, serializer } => {
+            let store = KV::new(serializer);
+            store.set_schema(key, schema)?;
+            "OK".into()
+        }
+        Command::Explain { key, serializer } => {
+            let store = KV::new(serializer);
+            store.explain(key)?
+        }
+        Command::Serve { addr, cache_size, serializer } => {
+            server::serve(addr, serializer, cache_size)?;
+            "OK".into()
+        }
+        Command::Import { path, serializer } => {
+            let store = KV::new(serializer);
+            store.import(path)?;
+            "OK".into()
+        }
+        Command::Export { path, serializer } => {
+            let store = KV::new(serializer);
+            store.export(path)?;
+            "OK".into()
+        }
     };
     println!("{:?}", value);
     Ok(())
@@ -75,6 +124,203 @@ pub enum KVError {
 
     #[error("Failed to write BSON data")]
     SerializeError(#[from] bson::ser::Error),
+
+    #[error("Cannot append to value of this type for key {0}")]
+    UnsupportedAppend(String),
+
+    #[error("Failed to read TOML data")]
+    TomlDeserializationError(#[from] toml::de::Error),
+
+    #[error("Failed to write TOML data")]
+    TomlSerializationError(#[from] toml::ser::Error),
+
+    #[error("Failed to read or write YAML data")]
+    YamlError(#[from] serde_yaml::Error),
+
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+
+    #[error("Path segment not found: {0}")]
+    PathNotFound(String),
+
+    #[error("Failed to read or write CSV data")]
+    CsvError(#[from] csv::Error),
+
+    #[error("TOML cannot represent a null value (key {0}); use Json, Bson or Yaml instead")]
+    UnsupportedTomlNull(String),
+}
+
+/// A value stored against a key. Mirrors the JSON data model so the store
+/// can hold structured data instead of forcing callers to pre-stringify.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Map<String, Value>),
+}
+
+impl Value {
+    /// Parses a raw CLI argument as JSON, falling back to a plain string
+    /// scalar when it isn't valid JSON (e.g. `hello` rather than `"hello"`).
+    fn parse(raw: &str) -> Value {
+        serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+    }
+}
+
+/// Reports metadata about a stored value without exposing the value itself,
+/// for the `Explain` command.
+pub trait ExplainValue {
+    /// The value's type name: `null`, `bool`, `number`, `string`, `array` or `object`.
+    fn get_type(&self) -> String;
+
+    /// The value's length where one is meaningful (byte length for strings,
+    /// element count for arrays, field count for objects), `None` for scalars.
+    fn get_len(&self) -> Option<u64>;
+}
+
+impl ExplainValue for Value {
+    fn get_type(&self) -> String {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::I64(_) | Value::U64(_) | Value::F64(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+        .to_string()
+    }
+
+    fn get_len(&self) -> Option<u64> {
+        match self {
+            Value::String(s) => Some(s.len() as u64),
+            Value::Array(items) => Some(items.len() as u64),
+            Value::Object(fields) => Some(fields.len() as u64),
+            _ => None,
+        }
+    }
+}
+
+/// One segment of a dotted key path: a `.field` object access or an
+/// `[index]` array access.
+#[derive(Debug, Clone)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+fn segment_label(segment: &PathSegment) -> String {
+    match segment {
+        PathSegment::Key(k) => k.clone(),
+        PathSegment::Index(i) => format!("[{}]", i),
+    }
+}
+
+/// Splits a dotted key path like `user.address.city` or `items[0].name`
+/// into its `.field` and `[index]` segments.
+fn parse_path(path: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let mut remainder = part;
+        if let Some(bracket) = remainder.find('[') {
+            let (name, rest) = remainder.split_at(bracket);
+            if !name.is_empty() {
+                segments.push(PathSegment::Key(name.to_string()));
+            }
+            remainder = rest;
+            while let Some(stripped) = remainder.strip_prefix('[') {
+                match stripped.find(']') {
+                    Some(end) => {
+                        if let Ok(index) = stripped[..end].parse::<usize>() {
+                            segments.push(PathSegment::Index(index));
+                        }
+                        remainder = &stripped[end + 1..];
+                    }
+                    None => break,
+                }
+            }
+        } else {
+            segments.push(PathSegment::Key(remainder.to_string()));
+        }
+    }
+    segments
+}
+
+/// Walks `path` inside `value`, erroring with the first missing segment
+/// rather than creating it.
+fn walk<'a>(value: &'a Value, path: &[PathSegment]) -> Result<&'a Value, String> {
+    let mut current = value;
+    for segment in path {
+        current = match (current, segment) {
+            (Value::Object(map), PathSegment::Key(k)) => {
+                map.get(k).ok_or_else(|| segment_label(segment))?
+            }
+            (Value::Array(items), PathSegment::Index(i)) => {
+                items.get(*i).ok_or_else(|| segment_label(segment))?
+            }
+            _ => return Err(segment_label(segment)),
+        };
+    }
+    Ok(current)
+}
+
+/// Mutable counterpart of [`walk`]: every segment must already exist, and
+/// the first missing one is returned as the error; used to locate a delete
+/// target's parent without auto-vivifying it (unlike [`walk_mut`]).
+fn walk_mut_existing<'a>(value: &'a mut Value, path: &[PathSegment]) -> Result<&'a mut Value, String> {
+    let mut current = value;
+    for segment in path {
+        current = match (current, segment) {
+            (Value::Object(map), PathSegment::Key(k)) => {
+                map.get_mut(k).ok_or_else(|| segment_label(segment))?
+            }
+            (Value::Array(items), PathSegment::Index(i)) => {
+                items.get_mut(*i).ok_or_else(|| segment_label(segment))?
+            }
+            _ => return Err(segment_label(segment)),
+        };
+    }
+    Ok(current)
+}
+
+/// Walks `path` inside `value`, auto-vivifying missing intermediate objects
+/// and arrays so `set` can create nested structure on the fly.
+fn walk_mut<'a>(value: &'a mut Value, path: &[PathSegment]) -> &'a mut Value {
+    let mut current = value;
+    for segment in path {
+        current = match segment {
+            PathSegment::Key(k) => {
+                if !matches!(current, Value::Object(_)) {
+                    *current = Value::Object(Map::new());
+                }
+                match current {
+                    Value::Object(map) => map.entry(k.clone()).or_insert(Value::Null),
+                    _ => unreachable!(),
+                }
+            }
+            PathSegment::Index(i) => {
+                if !matches!(current, Value::Array(_)) {
+                    *current = Value::Array(Vec::new());
+                }
+                match current {
+                    Value::Array(items) => {
+                        while items.len() <= *i {
+                            items.push(Value::Null);
+                        }
+                        &mut items[*i]
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        };
+    }
+    current
 }
 
 #[derive(Debug, StructOpt)]
@@ -146,14 +392,55 @@ enum Command {
         #[structopt(short = "p", long = "pattern")]
         pattern: String,
 
+        #[structopt(short = "s", long = "serializer", default_value = "Bson")]
+        serializer: Box<dyn BackendStorage>,
+    },
+    Schema {
+        #[structopt(short = "k", long = "key")]
+        key: String,
+
+        #[structopt(long = "schema")]
+        schema: String,
+
+        #[structopt(short = "s", long = "serializer", default_value = "Bson")]
+        serializer: Box<dyn BackendStorage>,
+    },
+    Explain {
+        #[structopt(short = "k", long = "key")]
+        key: String,
+
+        #[structopt(short = "s", long = "serializer", default_value = "Bson")]
+        serializer: Box<dyn BackendStorage>,
+    },
+    Serve {
+        #[structopt(short = "a", long = "addr", default_value = "127.0.0.1:7070")]
+        addr: String,
+
+        #[structopt(long = "cache-size", default_value = "1000")]
+        cache_size: usize,
+
+        #[structopt(short = "s", long = "serializer", default_value = "Bson")]
+        serializer: Box<dyn BackendStorage>,
+    },
+    Import {
+        #[structopt(short = "p", long = "path")]
+        path: String,
+
+        #[structopt(short = "s", long = "serializer", default_value = "Bson")]
+        serializer: Box<dyn BackendStorage>,
+    },
+    Export {
+        #[structopt(short = "p", long = "path")]
+        path: String,
+
         #[structopt(short = "s", long = "serializer", default_value = "Bson")]
         serializer: Box<dyn BackendStorage>,
     },
 }
 
-pub trait BackendStorage {
-    fn load_keys(&self) -> Result<HashMap<String, String>, KVError>;
-    fn write_keys(&self, map: HashMap<String, String>) -> Result<(), KVError>;
+pub trait BackendStorage: Send + Sync {
+    fn load_keys(&self) -> Result<Map<String, Value>, KVError>;
+    fn write_keys(&self, map: Map<String, Value>) -> Result<(), KVError>;
     fn clear(&self) -> Result<(), KVError>;
 }
 
@@ -163,7 +450,9 @@ impl std::str::FromStr for Box<dyn BackendStorage> {
         match s {
             "Json" => Ok(Box::new(JsonBackendStorage)),
             "Bson" => Ok(Box::new(BsonBackendStorage)),
-            _ => Err("Serializer must be either Json or Bson".to_string()),
+            "Toml" => Ok(Box::new(TomlBackendStorage)),
+            "Yaml" => Ok(Box::new(YamlBackendStorage)),
+            _ => Err("Serializer must be one of Json, Bson, Toml or Yaml".to_string()),
         }
     }
 }
@@ -178,59 +467,454 @@ impl std::fmt::Debug for Box<dyn BackendStorage> {
             .finish()
     }
 }
+/// Returns the path of the nth rolling backup for `path` (`.bak`, `.bak1`, ...).
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    if index == 0 {
+        name.push(".bak");
+    } else {
+        name.push(format!(".bak{}", index));
+    }
+    PathBuf::from(name)
+}
+
+/// Returns the sibling temp file a write is staged into before the atomic rename.
+fn sibling_tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Returns the sidecar file used to lock `path` for the duration of an
+/// `atomic_write` cycle. This is a stable file that is never renamed or
+/// rotated away, unlike `path` itself, so the lock can't be dropped out
+/// from under a concurrent writer mid-rotation.
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Maximum number of rolling backups kept per file; older backups are
+/// deleted as new ones roll in so the chain doesn't grow without bound.
+const MAX_BACKUPS: u32 = 5;
+
+/// Finds the rolling backups of `path` that currently exist on disk, as
+/// `(index, path)` pairs ordered from most recent (`.bak`, index 0) to oldest.
+fn indexed_backups(path: &Path) -> Vec<(u32, PathBuf)> {
+    let bak_regex = Regex::new(r"\.bak(\d*)$").unwrap();
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let file_name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return Vec::new(),
+    };
+    let mut backups: Vec<(u32, PathBuf)> = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(n) => n,
+                None => continue,
+            };
+            if let Some(stripped) = name.strip_prefix(file_name) {
+                if let Some(caps) = bak_regex.captures(stripped) {
+                    let index = caps
+                        .get(1)
+                        .map(|m| m.as_str())
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.parse::<u32>().unwrap_or(0))
+                        .unwrap_or(0);
+                    backups.push((index, entry.path()));
+                }
+            }
+        }
+    }
+    backups.sort_unstable_by_key(|(index, _)| *index);
+    backups
+}
+
+/// Finds the rolling backups of `path` that currently exist on disk, ordered
+/// from most recent (`.bak`) to oldest.
+fn existing_backups(path: &Path) -> Vec<PathBuf> {
+    indexed_backups(path).into_iter().map(|(_, p)| p).collect()
+}
+
+/// Rotates `path` into `.bak`, bumping any existing `.bak(\d*)` files up by
+/// one index so the most recent backup is always `.bak`. Backups beyond
+/// `MAX_BACKUPS` fall off the end of the chain and are deleted, so the
+/// rolling window stays bounded instead of growing forever.
+fn rotate_backups(path: &Path) -> Result<(), KVError> {
+    if !path.exists() {
+        return Ok(());
+    }
+    for (index, existing) in indexed_backups(path).into_iter().rev() {
+        if index + 1 >= MAX_BACKUPS {
+            std::fs::remove_file(&existing)?;
+        } else {
+            std::fs::rename(&existing, backup_path(path, index + 1))?;
+        }
+    }
+    std::fs::rename(path, backup_path(path, 0))?;
+    Ok(())
+}
+
+/// Writes `contents` to `path` crash-safely: the previous file is rotated
+/// into a numbered `.bak`, the new contents are written to a sibling temp
+/// file and `fsync`'d, then atomically renamed over `path`. An advisory
+/// exclusive lock is held on a stable sidecar `.lock` file (never renamed
+/// or rotated, unlike `path` itself) for the duration of the cycle so
+/// concurrent invocations don't race each other.
+fn atomic_write(path: &Path, contents: &[u8]) -> Result<(), KVError> {
+    let lock_file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(lock_path(path))?;
+    lock_file.lock_exclusive()?;
+
+    rotate_backups(path)?;
+
+    let tmp_path = sibling_tmp_path(path);
+    {
+        let mut tmp_file = std::fs::File::create(&tmp_path)?;
+        tmp_file.write_all(contents)?;
+        tmp_file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+
+    lock_file.unlock()?;
+    Ok(())
+}
+
+/// Loads `path` via `parse`, falling back to the most recent `.bak` that
+/// parses cleanly if the primary file is missing or corrupted.
+fn load_with_backup_fallback<T>(
+    path: &Path,
+    parse: impl Fn(&Path) -> Result<T, KVError>,
+) -> Result<T, KVError> {
+    match parse(path) {
+        Ok(value) => Ok(value),
+        Err(err) => {
+            for backup in existing_backups(path) {
+                if let Ok(value) = parse(&backup) {
+                    return Ok(value);
+                }
+            }
+            Err(err)
+        }
+    }
+}
+
 pub struct JsonBackendStorage;
 
 impl BackendStorage for JsonBackendStorage {
-    fn load_keys(&self) -> Result<HashMap<String, String>, KVError> {
-        let file = match std::fs::File::open("kv.db") {
-            Ok(file) => file,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
-            Err(e) => return Err(KVError::GenericError(e)),
-        };
-        let reader = std::io::BufReader::new(file);
-        let map = serde_json::from_reader(reader)?;
-        Ok(map)
+    fn load_keys(&self) -> Result<Map<String, Value>, KVError> {
+        let path = Path::new("kv.db");
+        if !path.exists() {
+            return Ok(Map::new());
+        }
+        load_with_backup_fallback(path, |path| {
+            let file = std::fs::File::open(path)?;
+            let reader = std::io::BufReader::new(file);
+            Ok(serde_json::from_reader(reader)?)
+        })
     }
 
-    fn write_keys(&self, map: HashMap<String, String>) -> Result<(), KVError> {
+    fn write_keys(&self, map: Map<String, Value>) -> Result<(), KVError> {
         let json_string = serde_json::to_string(&map)?;
-        std::fs::write("kv.db", json_string)?;
-        Ok(())
+        atomic_write(Path::new("kv.db"), json_string.as_bytes())
     }
 
     fn clear(&self) -> Result<(), KVError> {
-        std::fs::write("kv.db", "{}".to_string())?;
-        Ok(())
+        atomic_write(Path::new("kv.db"), b"{}")
     }
 }
 
 pub struct BsonBackendStorage;
 
 impl BackendStorage for BsonBackendStorage {
-    fn load_keys(&self) -> Result<HashMap<String, String>, KVError> {
-        let file = match std::fs::File::open("kv.bson") {
-            Ok(file) => file,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
-            Err(e) => return Err(KVError::GenericError(e)),
-        };
-        let mut reader = std::io::BufReader::new(file);
-        let document = bson::document::Document::from_reader(&mut reader)?;
-        let map = bson::from_bson(document.into())?;
-        Ok(map)
+    fn load_keys(&self) -> Result<Map<String, Value>, KVError> {
+        let path = Path::new("kv.bson");
+        if !path.exists() {
+            return Ok(Map::new());
+        }
+        load_with_backup_fallback(path, |path| {
+            let file = std::fs::File::open(path)?;
+            let mut reader = std::io::BufReader::new(file);
+            let document = bson::document::Document::from_reader(&mut reader)?;
+            Ok(bson::from_bson(document.into())?)
+        })
     }
 
-    fn write_keys(&self, map: HashMap<String, String>) -> Result<(), KVError> {
-        let bson = bson::to_document(&map)?;
-        let file = std::fs::File::create("kv.bson")?;
-        let mut buffer = std::io::BufWriter::new(file);
-        bson.to_writer(&mut buffer)?;
+    fn write_keys(&self, map: Map<String, Value>) -> Result<(), KVError> {
+        let document = bson::to_document(&map)?;
+        let mut buffer = Vec::new();
+        document.to_writer(&mut buffer)?;
+        atomic_write(Path::new("kv.bson"), &buffer)?;
         Ok(())
     }
 
     fn clear(&self) -> Result<(), KVError> {
-        std::fs::remove_file("kv.bson")?;
-        Ok(())
+        let document = bson::to_document(&Map::<String, Value>::new())?;
+        let mut buffer = Vec::new();
+        document.to_writer(&mut buffer)?;
+        atomic_write(Path::new("kv.bson"), &buffer)
+    }
+}
+
+/// The file holding per-key JSON Schemas (Draft 7) used to validate values on `set`.
+const SCHEMA_FILE: &str = "kv.schema.json";
+
+fn load_schemas() -> Result<std::collections::HashMap<String, serde_json::Value>, KVError> {
+    let path = Path::new(SCHEMA_FILE);
+    if !path.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let file = std::fs::File::open(path)?;
+    let reader = std::io::BufReader::new(file);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+fn write_schemas(
+    schemas: &std::collections::HashMap<String, serde_json::Value>,
+) -> Result<(), KVError> {
+    let json_string = serde_json::to_string(schemas)?;
+    atomic_write(Path::new(SCHEMA_FILE), json_string.as_bytes())
+}
+
+/// Returns `true` if `value` is, or nests, a `Value::Null` anywhere — the
+/// one JSON construct TOML has no way to represent (there is no null/unit
+/// type in the TOML data model).
+fn value_contains_null(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(items) => items.iter().any(value_contains_null),
+        Value::Object(fields) => fields.values().any(value_contains_null),
+        _ => false,
+    }
+}
+
+/// TOML has no null/unit type, so any value produced by an explicit
+/// `set -v null` or by `walk_mut`'s sparse-array auto-vivification (which
+/// pads skipped indices with `Value::Null`) can't round-trip through this
+/// backend. Reject it up front with a clear error instead of letting
+/// `toml::to_string` fail with an opaque serializer error.
+pub struct TomlBackendStorage;
+
+impl BackendStorage for TomlBackendStorage {
+    fn load_keys(&self) -> Result<Map<String, Value>, KVError> {
+        let path = Path::new("kv.toml");
+        if !path.exists() {
+            return Ok(Map::new());
+        }
+        load_with_backup_fallback(path, |path| {
+            let contents = std::fs::read_to_string(path)?;
+            Ok(toml::from_str(&contents)?)
+        })
+    }
+
+    fn write_keys(&self, map: Map<String, Value>) -> Result<(), KVError> {
+        if let Some(key) = map
+            .iter()
+            .find(|(_, value)| value_contains_null(value))
+            .map(|(key, _)| key.clone())
+        {
+            return Err(KVError::UnsupportedTomlNull(key));
+        }
+        let toml_string = toml::to_string(&map)?;
+        atomic_write(Path::new("kv.toml"), toml_string.as_bytes())
+    }
+
+    fn clear(&self) -> Result<(), KVError> {
+        atomic_write(Path::new("kv.toml"), b"")
+    }
+}
+
+pub struct YamlBackendStorage;
+
+impl BackendStorage for YamlBackendStorage {
+    fn load_keys(&self) -> Result<Map<String, Value>, KVError> {
+        let path = Path::new("kv.yaml");
+        if !path.exists() {
+            return Ok(Map::new());
+        }
+        load_with_backup_fallback(path, |path| {
+            let file = std::fs::File::open(path)?;
+            let reader = std::io::BufReader::new(file);
+            Ok(serde_yaml::from_reader(reader)?)
+        })
+    }
+
+    fn write_keys(&self, map: Map<String, Value>) -> Result<(), KVError> {
+        let yaml_string = serde_yaml::to_string(&map)?;
+        atomic_write(Path::new("kv.yaml"), yaml_string.as_bytes())
     }
+
+    fn clear(&self) -> Result<(), KVError> {
+        atomic_write(Path::new("kv.yaml"), b"{}")
+    }
+}
+
+/// Validates `value` against `key`'s registered JSON Schema, if any.
+/// Shared by the one-shot `KV::set` path and `Server::set` (chunk0-8) so a
+/// key with a registered schema can't be corrupted over one path just
+/// because it's rejected on the other.
+fn validate_value(
+    schemas: &std::collections::HashMap<String, serde_json::Value>,
+    key: &str,
+    value: &Value,
+) -> Result<(), KVError> {
+    let schema = match schemas.get(key) {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+    let compiled = jsonschema::JSONSchema::options()
+        .with_draft(jsonschema::Draft::Draft7)
+        .compile(schema)
+        .map_err(|e| KVError::ValidationError(e.to_string()))?;
+    let instance = serde_json::to_value(value)?;
+    if let Err(errors) = compiled.validate(&instance) {
+        let message = errors
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect::<Vec<_>>()
+            .join("; ");
+        return Err(KVError::ValidationError(message));
+    }
+    Ok(())
+}
+
+/// Resolves `key` (possibly a dotted path) against `map` and returns the
+/// leaf value. Shared by `KV::get` and `Server::get`.
+fn apply_get(map: &Map<String, Value>, key: &str) -> Result<Value, KVError> {
+    let segments = parse_path(key);
+    let mut parts = segments.into_iter();
+    let root_key = match parts.next() {
+        Some(PathSegment::Key(k)) => k,
+        _ => return Err(KVError::KeyNotFound(key.to_string())),
+    };
+    let rest: Vec<PathSegment> = parts.collect();
+    let root_value = map
+        .get(&root_key)
+        .ok_or_else(|| KVError::KeyNotFound(key.to_string()))?;
+    let leaf = walk(root_value, &rest).map_err(KVError::PathNotFound)?;
+    Ok(leaf.clone())
+}
+
+/// Writes `value` at `key` (possibly a dotted path) into `map`, validating
+/// the whole mutated root against its registered schema before committing
+/// it, and returns `(root_key, touched leaf)` for the caller to use for
+/// cache bookkeeping. Shared by `KV::set` and `Server::set`.
+fn apply_set(
+    map: &mut Map<String, Value>,
+    schemas: &std::collections::HashMap<String, serde_json::Value>,
+    key: &str,
+    value: &str,
+) -> Result<(String, Value), KVError> {
+    let parsed = Value::parse(value);
+    let segments = parse_path(key);
+    let mut parts = segments.into_iter();
+    let root_key = match parts.next() {
+        Some(PathSegment::Key(k)) => k,
+        _ => return Err(KVError::PathNotFound(key.to_string())),
+    };
+    let rest: Vec<PathSegment> = parts.collect();
+
+    // Mutate a copy of the root value first so a dotted-path write that
+    // fails validation never touches the stored map, and so the schema
+    // (registered against the *root* key) is always checked against the
+    // whole resulting value, not just the leaf being written.
+    let mut root_value = map.get(&root_key).cloned().unwrap_or(Value::Null);
+    let touched = if rest.is_empty() {
+        root_value = parsed;
+        root_value.clone()
+    } else {
+        let leaf = walk_mut(&mut root_value, &rest);
+        *leaf = parsed;
+        leaf.clone()
+    };
+    validate_value(schemas, &root_key, &root_value)?;
+    map.insert(root_key.clone(), root_value);
+    Ok((root_key, touched))
+}
+
+/// Removes `key` (possibly a dotted path) from `map` and returns the root
+/// key that was touched, for cache invalidation. Shared by `KV::delete`
+/// and `Server::del`.
+fn apply_delete(map: &mut Map<String, Value>, key: &str) -> Result<String, KVError> {
+    let segments = parse_path(key);
+    let mut parts = segments.into_iter();
+    let root_key = match parts.next() {
+        Some(PathSegment::Key(k)) => k,
+        _ => return Err(KVError::KeyNotFound(key.to_string())),
+    };
+    let rest: Vec<PathSegment> = parts.collect();
+
+    if rest.is_empty() {
+        return match map_remove(map, &root_key) {
+            Some(_) => Ok(root_key),
+            None => Err(KVError::KeyNotFound(key.to_string())),
+        };
+    }
+
+    let root_value = map
+        .get_mut(&root_key)
+        .ok_or_else(|| KVError::KeyNotFound(key.to_string()))?;
+    let (last, parent_path) = rest.split_last().expect("rest is non-empty");
+    let parent = walk_mut_existing(root_value, parent_path).map_err(KVError::PathNotFound)?;
+    match (parent, last) {
+        (Value::Object(obj), PathSegment::Key(k)) => {
+            map_remove(obj, k).ok_or_else(|| KVError::PathNotFound(k.clone()))?;
+        }
+        (Value::Array(items), PathSegment::Index(i)) if *i < items.len() => {
+            items.remove(*i);
+        }
+        _ => return Err(KVError::PathNotFound(segment_label(last))),
+    }
+    Ok(root_key)
+}
+
+/// Moves the value at `key` to `new_key`. Shared by `KV::rename` and
+/// `Server::rename`.
+fn apply_rename(map: &mut Map<String, Value>, key: &str, new_key: String) -> Result<(), KVError> {
+    match map_remove(map, key) {
+        Some(v) => {
+            map.insert(new_key, v);
+            Ok(())
+        }
+        None => Err(KVError::KeyNotFound(key.to_string())),
+    }
+}
+
+/// Appends `value` to the string or array stored at `key`, validating the
+/// resulting value against `key`'s registered schema before committing it
+/// (the same guarantee `apply_set` gives `set`). Shared by `KV::append` and
+/// `Server::append`.
+fn apply_append(
+    map: &mut Map<String, Value>,
+    schemas: &std::collections::HashMap<String, serde_json::Value>,
+    key: &str,
+    value: &str,
+) -> Result<(), KVError> {
+    let mut updated = match map.get(key) {
+        Some(Value::String(existing)) => {
+            let mut existing = existing.clone();
+            existing.push_str(value);
+            Value::String(existing)
+        }
+        Some(Value::Array(items)) => {
+            let mut items = items.clone();
+            items.push(Value::parse(value));
+            Value::Array(items)
+        }
+        Some(_) => return Err(KVError::UnsupportedAppend(key.to_string())),
+        None => return Err(KVError::KeyNotFound(key.to_string())),
+    };
+    validate_value(schemas, key, &updated)?;
+    std::mem::swap(map.get_mut(key).expect("checked above"), &mut updated);
+    Ok(())
 }
 
 pub struct KV {
@@ -255,46 +939,39 @@ impl KV {
     }
 
     fn append(&self, key: String, value: String) -> Result<(), KVError> {
+        let schemas = load_schemas()?;
         let mut map = self.storage.load_keys()?;
-        let result = map.get_mut(&key);
-        match result {
-            Some(v) => {
-                *v = format!("{}{}", v, value);
-                self.storage.write_keys(map)?;
-                Ok(())
-            }
-            None => Err(KVError::KeyNotFound(key)),
-        }
+        apply_append(&mut map, &schemas, &key, &value)?;
+        self.storage.write_keys(map)?;
+        Ok(())
     }
 
     fn rename(&self, key: String, new_key: String) -> Result<(), KVError> {
         let mut map = self.storage.load_keys()?;
-        let value = map.remove(&key);
-        match value {
-            Some(v) => {
-                map.insert(new_key, v);
-                self.storage.write_keys(map)?;
-                Ok(())
-            }
-            None => Err(KVError::KeyNotFound(key)),
-        }
+        apply_rename(&mut map, &key, new_key)?;
+        self.storage.write_keys(map)?;
+        Ok(())
     }
 
     fn exists(&self, key: String) -> Result<bool, KVError> {
         let map = self.storage.load_keys()?;
-        Ok(map.contains_key(&key))
+        let segments = parse_path(&key);
+        let mut parts = segments.into_iter();
+        let root_key = match parts.next() {
+            Some(PathSegment::Key(k)) => k,
+            _ => return Ok(false),
+        };
+        let rest: Vec<PathSegment> = parts.collect();
+        Ok(map
+            .get(&root_key)
+            .is_some_and(|v| walk(v, &rest).is_ok()))
     }
 
     fn delete(&self, key: String) -> Result<(), KVError> {
         let mut map = self.storage.load_keys()?;
-        let value = map.remove(&key);
-        match value {
-            Some(_value) => {
-                self.storage.write_keys(map)?;
-                Ok(())
-            }
-            None => Err(KVError::KeyNotFound(key)),
-        }
+        apply_delete(&mut map, &key)?;
+        self.storage.write_keys(map)?;
+        Ok(())
     }
 
     fn clear(&self) -> Result<(), KVError> {
@@ -303,21 +980,87 @@ impl KV {
 
     fn get(&self, key: String) -> Result<String, KVError> {
         let map = self.storage.load_keys()?;
-        let value = map.get(&key);
-        //value.ok_or(Err(KVError::KeyNotFound(key)))
-        match value {
-            Some(value) => Ok(value.into()),
-            None => Err(KVError::KeyNotFound(key)),
-        }
+        let leaf = apply_get(&map, &key)?;
+        Ok(serde_json::to_string(&leaf)?)
     }
 
     fn set(&self, key: String, value: String) -> Result<(), KVError> {
+        let schemas = load_schemas()?;
         let mut map = self.storage.load_keys()?;
-        map.insert(key, value);
+        apply_set(&mut map, &schemas, &key, &value)?;
         self.storage.write_keys(map)?;
         Ok(())
     }
 
+    fn set_schema(&self, key: String, schema: String) -> Result<(), KVError> {
+        let schema: serde_json::Value = serde_json::from_str(&schema)?;
+        let mut schemas = load_schemas()?;
+        schemas.insert(key, schema);
+        write_schemas(&schemas)
+    }
+
+    /// Bulk-loads `path` into the store in one pass: a `.csv` file is read
+    /// as `key,value` rows via the `csv` crate, anything else is read as a
+    /// JSON object. Every row is routed through `apply_set` (the same
+    /// helper `KV::set` and `Server::set` use), so a dotted-path key like
+    /// `user.city` nests into `user` instead of becoming a literal top-level
+    /// key, and a schema registered against the row's root key is enforced
+    /// just like it would be for a `set`. The whole import is applied to a
+    /// single in-memory map before issuing exactly one `write_keys` call.
+    fn import(&self, path: String) -> Result<(), KVError> {
+        let schemas = load_schemas()?;
+        let mut map = self.storage.load_keys()?;
+        if path.ends_with(".csv") {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_path(&path)?;
+            for record in reader.records() {
+                let record = record?;
+                if let (Some(key), Some(value)) = (record.get(0), record.get(1)) {
+                    apply_set(&mut map, &schemas, key, value)?;
+                }
+            }
+        } else {
+            let file = std::fs::File::open(&path)?;
+            let reader = std::io::BufReader::new(file);
+            let imported: Map<String, Value> = serde_json::from_reader(reader)?;
+            for (key, value) in imported {
+                let raw = serde_json::to_string(&value)?;
+                apply_set(&mut map, &schemas, &key, &raw)?;
+            }
+        }
+        self.storage.write_keys(map)?;
+        Ok(())
+    }
+
+    /// Dumps the whole store to `path` in one pass: a `.csv` path is written
+    /// as `key,value` rows, anything else is written as a JSON object.
+    fn export(&self, path: String) -> Result<(), KVError> {
+        let map = self.storage.load_keys()?;
+        if path.ends_with(".csv") {
+            let mut writer = csv::Writer::from_path(&path)?;
+            for (key, value) in map.iter() {
+                writer.write_record([key.clone(), serde_json::to_string(value)?])?;
+            }
+            writer.flush()?;
+        } else {
+            let json_string = serde_json::to_string(&map)?;
+            std::fs::write(&path, json_string)?;
+        }
+        Ok(())
+    }
+
+    fn explain(&self, key: String) -> Result<String, KVError> {
+        let map = self.storage.load_keys()?;
+        match map.get(&key) {
+            Some(value) => Ok(match value.get_len() {
+                Some(len) => format!("type: {}, len: {}", value.get_type(), len),
+                None => format!("type: {}", value.get_type()),
+            }),
+            None => Err(KVError::KeyNotFound(key)),
+        }
+    }
+
     //#[test]
     // fn get_keys_returns_keys(){
     //     flush_all().unwrap();
@@ -343,3 +1086,390 @@ impl KV {
     //     }
     // }
 }
+
+#[cfg(test)]
+mod typed_value_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn set_parses_each_json_scalar_and_structured_type() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            let cases = [
+                ("n", "42", "42"),
+                ("f", "3.14", "3.14"),
+                ("b", "true", "true"),
+                ("arr", "[1,2,3]", "[1,2,3]"),
+                ("obj", r#"{"a":1}"#, r#"{"a":1}"#),
+            ];
+            for (key, value, expected) in cases {
+                store.set(key.to_string(), value.to_string()).unwrap();
+                assert_eq!(store.get(key.to_string()).unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn set_falls_back_to_a_plain_string_for_non_json_input() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store.set("greeting".to_string(), "hello".to_string()).unwrap();
+            assert_eq!(store.get("greeting".to_string()).unwrap(), "\"hello\"");
+        });
+    }
+}
+
+#[cfg(test)]
+mod explain_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn explain_reports_type_and_length_for_arrays_and_objects() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store.set("arr".to_string(), "[1,2,3]".to_string()).unwrap();
+            assert_eq!(store.explain("arr".to_string()).unwrap(), "type: array, len: 3");
+
+            store.set("obj".to_string(), r#"{"a":1,"b":2}"#.to_string()).unwrap();
+            assert_eq!(store.explain("obj".to_string()).unwrap(), "type: object, len: 2");
+        });
+    }
+
+    #[test]
+    fn explain_reports_only_the_type_for_scalars() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store.set("n".to_string(), "42".to_string()).unwrap();
+            assert_eq!(store.explain("n".to_string()).unwrap(), "type: number");
+
+            store.set("b".to_string(), "true".to_string()).unwrap();
+            assert_eq!(store.explain("b".to_string()).unwrap(), "type: bool");
+        });
+    }
+
+    #[test]
+    fn explain_on_a_missing_key_returns_key_not_found() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            let err = store.explain("missing".to_string()).unwrap_err();
+            assert!(matches!(err, KVError::KeyNotFound(_)));
+        });
+    }
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn set_on_a_dotted_path_validates_the_whole_root_against_its_schema() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store
+                .set_schema(
+                    "user".to_string(),
+                    r#"{"type":"object","properties":{"age":{"type":"number"}}}"#.to_string(),
+                )
+                .unwrap();
+            store.set("user".to_string(), r#"{"age":30}"#.to_string()).unwrap();
+
+            let err = store
+                .set("user.age".to_string(), "not-a-number".to_string())
+                .unwrap_err();
+            assert!(matches!(err, KVError::ValidationError(_)));
+
+            // the rejected write must not have been applied
+            let value = store.get("user.age".to_string()).unwrap();
+            assert_eq!(value, "30");
+        });
+    }
+
+    #[test]
+    fn append_validates_the_result_against_the_registered_schema() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store
+                .set_schema(
+                    "arr".to_string(),
+                    r#"{"type":"array","maxItems":2}"#.to_string(),
+                )
+                .unwrap();
+            store.set("arr".to_string(), "[1,2]".to_string()).unwrap();
+
+            let err = store.append("arr".to_string(), "3".to_string()).unwrap_err();
+            assert!(matches!(err, KVError::ValidationError(_)));
+
+            // the rejected append must not have been applied
+            let value = store.get("arr".to_string()).unwrap();
+            assert_eq!(value, "[1,2]");
+        });
+    }
+}
+
+#[cfg(test)]
+mod backup_tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // `atomic_write` and friends operate on paths relative to the process's
+    // current directory, so tests that exercise them can't run concurrently
+    // with each other without stepping on one another's files. Every test
+    // module that changes the current directory takes this one lock.
+    pub(crate) static FS_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn first_write_in_a_fresh_directory_creates_no_backup() {
+        in_temp_dir(|| {
+            atomic_write(Path::new("kv.db"), b"{}").unwrap();
+            assert!(existing_backups(Path::new("kv.db")).is_empty());
+        });
+    }
+
+    #[test]
+    fn atomic_write_locks_a_stable_sidecar_not_the_data_file() {
+        in_temp_dir(|| {
+            let path = Path::new("kv.db");
+            atomic_write(path, b"{}").unwrap();
+            assert!(lock_path(path).exists());
+            assert!(path.exists());
+        });
+    }
+
+    #[test]
+    fn load_with_backup_fallback_recovers_from_a_corrupted_primary() {
+        in_temp_dir(|| {
+            let storage = JsonBackendStorage;
+            let mut first = Map::new();
+            first.insert("a".to_string(), Value::String("1".to_string()));
+            storage.write_keys(first).unwrap();
+
+            let mut second = Map::new();
+            second.insert("a".to_string(), Value::String("2".to_string()));
+            storage.write_keys(second).unwrap();
+
+            std::fs::write("kv.db", b"not json").unwrap();
+
+            let loaded = storage.load_keys().unwrap();
+            assert_eq!(loaded.get("a"), Some(&Value::String("1".to_string())));
+        });
+    }
+
+    #[test]
+    fn rotate_backups_caps_the_chain_at_max_backups() {
+        in_temp_dir(|| {
+            let path = Path::new("kv.db");
+            for i in 0..(MAX_BACKUPS + 3) {
+                std::fs::write(path, i.to_string()).unwrap();
+                rotate_backups(path).unwrap();
+            }
+            assert!(existing_backups(path).len() <= MAX_BACKUPS as usize);
+        });
+    }
+}
+
+#[cfg(test)]
+mod import_export_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn csv_export_then_import_round_trips_every_entry() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store.set("a".to_string(), "1".to_string()).unwrap();
+            store.set("b".to_string(), "2".to_string()).unwrap();
+            store.export("dump.csv".to_string()).unwrap();
+
+            store.clear().unwrap();
+            store.import("dump.csv".to_string()).unwrap();
+
+            assert_eq!(store.get("a".to_string()).unwrap(), "1");
+            assert_eq!(store.get("b".to_string()).unwrap(), "2");
+        });
+    }
+
+    #[test]
+    fn csv_import_nests_dotted_path_keys_instead_of_storing_them_literally() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            std::fs::write("dump.csv", "user.city,NYC\n").unwrap();
+            store.import("dump.csv".to_string()).unwrap();
+
+            assert_eq!(store.get("user.city".to_string()).unwrap(), "\"NYC\"");
+            assert!(store.get("user.city".to_string()).is_ok());
+        });
+    }
+
+    #[test]
+    fn csv_import_enforces_the_registered_schema_for_the_row() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store
+                .set_schema("age".to_string(), r#"{"type":"number"}"#.to_string())
+                .unwrap();
+            std::fs::write("dump.csv", "age,not-a-number\n").unwrap();
+
+            let err = store.import("dump.csv".to_string()).unwrap_err();
+            assert!(matches!(err, KVError::ValidationError(_)));
+            assert!(store.get("age".to_string()).is_err());
+        });
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+#[cfg(test)]
+mod preserve_order_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn keys_preserve_insertion_order_across_a_delete() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(JsonBackendStorage));
+            store.set("z".to_string(), "1".to_string()).unwrap();
+            store.set("a".to_string(), "2".to_string()).unwrap();
+            store.set("m".to_string(), "3".to_string()).unwrap();
+            store.delete("a".to_string()).unwrap();
+
+            let keys = store.get_keys(".*".to_string()).unwrap();
+            assert_eq!(keys, vec!["z".to_string(), "m".to_string()]);
+        });
+    }
+}
+
+#[cfg(test)]
+mod structured_backend_tests {
+    use super::*;
+
+    // See backup_tests::FS_TEST_LOCK: storage paths are relative to the
+    // process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    #[test]
+    fn toml_backend_rejects_an_explicit_null_with_a_clear_error() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(TomlBackendStorage));
+            let err = store.set("x".to_string(), "null".to_string()).unwrap_err();
+            assert!(matches!(err, KVError::UnsupportedTomlNull(ref k) if k == "x"));
+        });
+    }
+
+    #[test]
+    fn toml_backend_rejects_a_sparse_array_padded_with_null() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(TomlBackendStorage));
+            // walk_mut pads indices 0..3 with Value::Null to reach index 3.
+            let err = store.set("arr[3]".to_string(), "9".to_string()).unwrap_err();
+            assert!(matches!(err, KVError::UnsupportedTomlNull(ref k) if k == "arr"));
+        });
+    }
+
+    #[test]
+    fn toml_backend_round_trips_non_null_values() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(TomlBackendStorage));
+            store.set("a".to_string(), r#"{"b":1}"#.to_string()).unwrap();
+            assert_eq!(store.get("a".to_string()).unwrap(), r#"{"b":1}"#);
+        });
+    }
+
+    #[test]
+    fn yaml_backend_round_trips_an_explicit_null_and_a_sparse_array() {
+        in_temp_dir(|| {
+            let store = KV::new(Box::new(YamlBackendStorage));
+            store.set("x".to_string(), "null".to_string()).unwrap();
+            assert_eq!(store.get("x".to_string()).unwrap(), "null");
+
+            store.set("arr[2]".to_string(), "9".to_string()).unwrap();
+            assert_eq!(store.get("arr".to_string()).unwrap(), "[null,null,9]");
+        });
+    }
+}
@@ -0,0 +1,356 @@
+//! Persistent server mode: loads the store once and keeps it resident in
+//! memory, answering requests over a line-based TCP or Unix-socket protocol
+//! instead of reloading and reparsing the whole file on every command.
+
+use crate::{apply_append, apply_delete, apply_get, apply_rename, apply_set, load_schemas};
+use crate::{BackendStorage, KVError, Map, Value};
+use lru::LruCache;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZeroUsize;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+
+/// Shared state behind a `Serve` session: the fully resident map (the
+/// source of truth between flushes) plus an LRU of recently-touched
+/// entries, sized by `--cache-size`, so hot keys don't need to walk the
+/// full map.
+struct Server {
+    storage: Box<dyn BackendStorage>,
+    state: Mutex<Map<String, Value>>,
+    hot: Mutex<LruCache<String, Value>>,
+}
+
+/// The root key of a dotted/indexed path (`"user"` for both `"user"` and
+/// `"user.address[0]"`), i.e. the key a write or delete actually mutates.
+fn key_root(key: &str) -> &str {
+    let end = key
+        .find(['.', '['])
+        .unwrap_or(key.len());
+    &key[..end]
+}
+
+impl Server {
+    fn new(storage: Box<dyn BackendStorage>, cache_size: usize) -> Result<Self, KVError> {
+        let state = storage.load_keys()?;
+        let capacity = NonZeroUsize::new(cache_size).unwrap_or(NonZeroUsize::new(1000).unwrap());
+        Ok(Self {
+            storage,
+            state: Mutex::new(state),
+            hot: Mutex::new(LruCache::new(capacity)),
+        })
+    }
+
+    fn touch(&self, key: &str, value: &Value) {
+        self.hot.lock().unwrap().put(key.to_string(), value.clone());
+    }
+
+    /// Drops every hot-cache entry rooted at `root` (e.g. a write or delete
+    /// to `"user"` must also evict a cached `"user.age"`, since the two
+    /// share the same underlying data and the literal-key cache has no
+    /// other way to know they're related).
+    ///
+    /// Callers must invoke this (and `touch`) while still holding the
+    /// `state` lock for the same mutation, not after dropping it — otherwise
+    /// two concurrent writers to the same key can have their cache updates
+    /// reordered relative to their (mutex-ordered) state writes, leaving
+    /// `hot` serving a value older than what's on disk.
+    fn invalidate_root(&self, root: &str) {
+        let mut hot = self.hot.lock().unwrap();
+        let stale: Vec<String> = hot
+            .iter()
+            .map(|(k, _)| k.clone())
+            .filter(|k| key_root(k) == root)
+            .collect();
+        for key in stale {
+            hot.pop(&key);
+        }
+    }
+
+    fn flush(&self, state: &Map<String, Value>) -> Result<(), KVError> {
+        self.storage.write_keys(state.clone())
+    }
+
+    /// Handles one line of the wire protocol and returns the response line.
+    fn handle(&self, line: &str) -> String {
+        let mut parts = line.splitn(2, ' ');
+        let command = parts.next().unwrap_or("").to_ascii_uppercase();
+        let rest = parts.next().unwrap_or("");
+        match command.as_str() {
+            "GET" => self.get(rest),
+            "SET" => self.set(rest),
+            "DEL" => self.del(rest),
+            "EXISTS" => self.exists(rest),
+            "RENAME" => self.rename(rest),
+            "APPEND" => self.append(rest),
+            "KEYS" => self.keys(rest),
+            "CLEAR" => self.clear(),
+            "" => "ERR empty command".to_string(),
+            other => format!("ERR unknown command {}", other),
+        }
+    }
+
+    fn get(&self, key: &str) -> String {
+        if let Some(value) = self.hot.lock().unwrap().get(key) {
+            return serde_json::to_string(value).unwrap_or_default();
+        }
+        let state = self.state.lock().unwrap();
+        match apply_get(&state, key) {
+            Ok(leaf) => {
+                self.touch(key, &leaf);
+                drop(state);
+                serde_json::to_string(&leaf).unwrap_or_default()
+            }
+            Err(e) => format_err(e),
+        }
+    }
+
+    fn set(&self, rest: &str) -> String {
+        let mut parts = rest.splitn(2, ' ');
+        let key = parts.next().unwrap_or("").to_string();
+        let raw_value = parts.next().unwrap_or("");
+        let schemas = match load_schemas() {
+            Ok(schemas) => schemas,
+            Err(e) => return format_err(e),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match apply_set(&mut state, &schemas, &key, raw_value) {
+            Ok((root_key, touched)) => match self.flush(&state) {
+                Ok(()) => {
+                    self.invalidate_root(&root_key);
+                    self.touch(&key, &touched);
+                    drop(state);
+                    "OK".to_string()
+                }
+                Err(e) => format_err(e),
+            },
+            Err(e) => format_err(e),
+        }
+    }
+
+    fn del(&self, key: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        match apply_delete(&mut state, key) {
+            Ok(root_key) => match self.flush(&state) {
+                Ok(()) => {
+                    self.invalidate_root(&root_key);
+                    drop(state);
+                    "OK".to_string()
+                }
+                Err(e) => format_err(e),
+            },
+            Err(e) => format_err(e),
+        }
+    }
+
+    fn exists(&self, key: &str) -> String {
+        let state = self.state.lock().unwrap();
+        if apply_get(&state, key).is_ok() {
+            "OK".to_string()
+        } else {
+            "Not exists".to_string()
+        }
+    }
+
+    fn rename(&self, rest: &str) -> String {
+        let mut parts = rest.splitn(2, ' ');
+        let key = parts.next().unwrap_or("").to_string();
+        let new_key = parts.next().unwrap_or("").to_string();
+        let mut state = self.state.lock().unwrap();
+        match apply_rename(&mut state, &key, new_key.clone()) {
+            Ok(()) => match self.flush(&state) {
+                Ok(()) => {
+                    self.invalidate_root(key_root(&key));
+                    self.invalidate_root(key_root(&new_key));
+                    drop(state);
+                    "OK".to_string()
+                }
+                Err(e) => format_err(e),
+            },
+            Err(e) => format_err(e),
+        }
+    }
+
+    fn append(&self, rest: &str) -> String {
+        let mut parts = rest.splitn(2, ' ');
+        let key = parts.next().unwrap_or("").to_string();
+        let value = parts.next().unwrap_or("");
+        let schemas = match load_schemas() {
+            Ok(schemas) => schemas,
+            Err(e) => return format_err(e),
+        };
+        let mut state = self.state.lock().unwrap();
+        match apply_append(&mut state, &schemas, &key, value) {
+            Ok(()) => match self.flush(&state) {
+                Ok(()) => {
+                    self.invalidate_root(key_root(&key));
+                    drop(state);
+                    "OK".to_string()
+                }
+                Err(e) => format_err(e),
+            },
+            Err(e) => format_err(e),
+        }
+    }
+
+    fn keys(&self, pattern: &str) -> String {
+        let regex = match regex::Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => return format!("ERR {}", e),
+        };
+        let state = self.state.lock().unwrap();
+        let keys = state
+            .keys()
+            .filter(|k| regex.is_match(k))
+            .cloned()
+            .collect::<Vec<_>>();
+        format!("Keys : {}", keys.join(", "))
+    }
+
+    fn clear(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+        state.clear();
+        match self.storage.clear() {
+            Ok(()) => {
+                self.hot.lock().unwrap().clear();
+                "OK".to_string()
+            }
+            Err(e) => format_err(e),
+        }
+    }
+}
+
+/// Formats a `KVError` as a wire-protocol error line, preserving the exact
+/// phrasing the hand-rolled `Server` methods used to produce for these
+/// cases before they were routed through the shared `apply_*` helpers.
+fn format_err(err: KVError) -> String {
+    match err {
+        KVError::KeyNotFound(k) => format!("ERR key not found {}", k),
+        KVError::PathNotFound(p) => format!("ERR path not found {}", p),
+        KVError::UnsupportedAppend(k) => {
+            format!("ERR cannot append to value of this type for key {}", k)
+        }
+        other => format!("ERR {}", other),
+    }
+}
+
+fn serve_connection(server: &Arc<Server>, reader: impl BufRead, mut writer: impl Write) {
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let response = server.handle(&line);
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+fn serve_tcp(server: Arc<Server>, addr: &str) -> Result<(), KVError> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let stream: TcpStream = stream?;
+        let server = Arc::clone(&server);
+        std::thread::spawn(move || {
+            if let Ok(writer) = stream.try_clone() {
+                serve_connection(&server, BufReader::new(stream), writer);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve_unix(server: Arc<Server>, path: &str) -> Result<(), KVError> {
+    let listener = UnixListener::bind(path)?;
+    for stream in listener.incoming() {
+        let stream: UnixStream = stream?;
+        let server = Arc::clone(&server);
+        std::thread::spawn(move || {
+            if let Ok(writer) = stream.try_clone() {
+                serve_connection(&server, BufReader::new(stream), writer);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Starts a resident KV server on `addr`. An address of the form
+/// `unix:/path/to/socket` binds a Unix domain socket; anything else is
+/// treated as a TCP `host:port` address.
+pub fn serve(addr: String, storage: Box<dyn BackendStorage>, cache_size: usize) -> Result<(), KVError> {
+    let server = Arc::new(Server::new(storage, cache_size)?);
+    match addr.strip_prefix("unix:") {
+        Some(path) => serve_unix(server, path),
+        None => serve_tcp(server, &addr),
+    }
+}
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::JsonBackendStorage;
+
+    // See main.rs's backup_tests::FS_TEST_LOCK: storage paths are relative
+    // to the process's current directory.
+    use crate::backup_tests::FS_TEST_LOCK;
+
+    fn in_temp_dir<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = FS_TEST_LOCK.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        let result = f();
+        std::env::set_current_dir(original).unwrap();
+        result
+    }
+
+    fn server() -> Server {
+        Server::new(Box::new(JsonBackendStorage), 1000).unwrap()
+    }
+
+    #[test]
+    fn set_then_get_round_trips_a_value() {
+        in_temp_dir(|| {
+            let server = server();
+            assert_eq!(server.handle("SET a 1"), "OK");
+            assert_eq!(server.handle("GET a"), "1");
+        });
+    }
+
+    #[test]
+    fn overwriting_a_root_key_invalidates_a_cached_nested_path() {
+        in_temp_dir(|| {
+            let server = server();
+            server.handle("SET user {\"age\":30}");
+            // populate the hot cache for the nested path
+            assert_eq!(server.handle("GET user.age"), "30");
+
+            server.handle("SET user {\"other\":1}");
+
+            // the stale "user.age" cache entry must not resurface
+            assert!(server.handle("GET user.age").starts_with("ERR"));
+        });
+    }
+
+    #[test]
+    fn append_with_a_registered_schema_rejects_an_invalid_result() {
+        in_temp_dir(|| {
+            let server = server();
+            server.handle("SET arr [1,2]");
+            std::fs::write(
+                "kv.schema.json",
+                r#"{"arr":{"type":"array","maxItems":2}}"#,
+            )
+            .unwrap();
+
+            let response = server.handle("APPEND arr 3");
+            assert!(response.starts_with("ERR"));
+            assert_eq!(server.handle("GET arr"), "[1,2]");
+        });
+    }
+}